@@ -0,0 +1,184 @@
+//! Typed node configuration and a fluent builder around
+//! [`amaru::stages::Config`].
+//!
+//! `launch_amaru` used to construct `Config` inline with hardcoded peers, store
+//! types and `migrate_chain_db`, none of which were tunable without
+//! recompiling. [`IcarusNodeBuilder`] makes that surface discoverable and
+//! UI-drivable: setters mirror the knobs the frontend exposes, `build()`
+//! validates peer addresses and wires up the per-network RocksDB stores, and
+//! [`NodeConfig`] is the serialisable form persisted in `store.json`.
+
+use std::path::PathBuf;
+
+use amaru::stages::{Config, StoreType};
+use amaru_kernel::network::NetworkName;
+use amaru_stores::rocksdb::RocksDbConfig;
+use serde::{Deserialize, Serialize};
+
+/// Backing store for the ledger/chain. Only RocksDB exists today, but this is
+/// serialised into `store.json`, so it is modelled as an enum for forward
+/// compatibility.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    RocksDb,
+}
+
+/// Serialisable node configuration, persisted per network under the
+/// `"node_config.<network>"` key (e.g. `"node_config.preprod"`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeConfig {
+    pub network: String,
+    pub peers: Vec<String>,
+    pub ledger_store: StoreKind,
+    pub chain_store: StoreKind,
+    pub migrate_chain_db: bool,
+}
+
+/// Fluent builder for a node configuration.
+pub struct IcarusNodeBuilder {
+    network: NetworkName,
+    peers: Vec<String>,
+    ledger_store: StoreKind,
+    chain_store: StoreKind,
+    migrate_chain_db: bool,
+}
+
+impl IcarusNodeBuilder {
+    /// Start from the defaults for `network` and the given upstream peers.
+    pub fn new(network: NetworkName, peers: Vec<String>) -> Self {
+        IcarusNodeBuilder {
+            network,
+            peers,
+            ledger_store: StoreKind::default(),
+            chain_store: StoreKind::default(),
+            migrate_chain_db: true,
+        }
+    }
+
+    pub fn network(mut self, network: NetworkName) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn add_peer(mut self, peer: impl Into<String>) -> Self {
+        self.peers.push(peer.into());
+        self
+    }
+
+    pub fn ledger_store(mut self, kind: StoreKind) -> Self {
+        self.ledger_store = kind;
+        self
+    }
+
+    pub fn chain_store(mut self, kind: StoreKind) -> Self {
+        self.chain_store = kind;
+        self
+    }
+
+    pub fn migrate_chain_db(mut self, migrate: bool) -> Self {
+        self.migrate_chain_db = migrate;
+        self
+    }
+
+    /// The network this builder targets, so the caller can resolve per-network
+    /// store directories.
+    pub fn target_network(&self) -> NetworkName {
+        self.network
+    }
+
+    /// Validate the configuration and construct the `amaru` [`Config`], wiring
+    /// the stores to the already-resolved per-network directories.
+    pub fn build(self, ledger_dir: PathBuf, chain_dir: PathBuf) -> Result<Config, String> {
+        if self.peers.is_empty() {
+            return Err("at least one upstream peer is required".into());
+        }
+        for peer in &self.peers {
+            validate_peer(peer)?;
+        }
+        Ok(Config {
+            upstream_peers: self.peers,
+            ledger_store: store_type(self.ledger_store, ledger_dir),
+            chain_store: store_type(self.chain_store, chain_dir),
+            migrate_chain_db: self.migrate_chain_db,
+            ..Config::default()
+        })
+    }
+}
+
+impl From<&NodeConfig> for IcarusNodeBuilder {
+    fn from(config: &NodeConfig) -> Self {
+        IcarusNodeBuilder {
+            network: parse_network(&config.network),
+            peers: config.peers.clone(),
+            ledger_store: config.ledger_store,
+            chain_store: config.chain_store,
+            migrate_chain_db: config.migrate_chain_db,
+        }
+    }
+}
+
+fn store_type(kind: StoreKind, dir: PathBuf) -> StoreType {
+    match kind {
+        StoreKind::RocksDb => StoreType::RocksDb(RocksDbConfig::new(dir)),
+    }
+}
+
+/// Parse a `store.json` network label, defaulting to Preprod.
+pub fn parse_network(label: &str) -> NetworkName {
+    match label {
+        "Mainnet" => NetworkName::Mainnet,
+        "Preview" => NetworkName::Preview,
+        _ => NetworkName::Preprod,
+    }
+}
+
+/// A peer must be a `host:port` pair with a non-empty host and a numeric port.
+fn validate_peer(peer: &str) -> Result<(), String> {
+    let (host, port) = peer
+        .rsplit_once(':')
+        .ok_or_else(|| format!("peer `{peer}` must be in host:port form"))?;
+    if host.is_empty() {
+        return Err(format!("peer `{peer}` has an empty host"));
+    }
+    port.parse::<u16>()
+        .map_err(|_| format!("peer `{peer}` has an invalid port"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_peer_accepts_host_port() {
+        assert!(validate_peer("preprod-node.play.dev.cardano.org:3001").is_ok());
+        assert!(validate_peer("127.0.0.1:3001").is_ok());
+    }
+
+    #[test]
+    fn validate_peer_rejects_malformed() {
+        assert!(validate_peer("no-port").is_err());
+        assert!(validate_peer(":3001").is_err());
+        assert!(validate_peer("host:not-a-port").is_err());
+        assert!(validate_peer("host:99999").is_err());
+    }
+
+    #[test]
+    fn parse_network_round_trips_known_labels() {
+        assert_eq!(parse_network("Mainnet"), NetworkName::Mainnet);
+        assert_eq!(parse_network("Preview"), NetworkName::Preview);
+        assert_eq!(parse_network("PreProd"), NetworkName::Preprod);
+        // Unknown labels fall back to Preprod.
+        assert_eq!(parse_network("whatever"), NetworkName::Preprod);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_peer() {
+        let err = IcarusNodeBuilder::new(NetworkName::Preprod, vec![])
+            .add_peer("bad-peer")
+            .build("ledger".into(), "chain".into());
+        assert!(err.is_err());
+    }
+}
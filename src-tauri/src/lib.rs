@@ -1,9 +1,16 @@
-use amaru::{
-    bootstrap::bootstrap,
-    stages::{build_and_run_network, Config},
-};
+mod config;
+mod p2p;
+mod sink;
+mod snapshot;
+
+use config::{IcarusNodeBuilder, NodeConfig};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sink::{SinkConfig, SinkSet};
+
+use amaru::{bootstrap::bootstrap, stages::build_and_run_network};
 use amaru_kernel::{Epoch, Slot, network::NetworkName};
-use amaru_stores::rocksdb::RocksDbConfig;
 use amaru_tracing_json::{JsonLayer, JsonTraceCollector};
 use serde::Serialize;
 use serde_json::json;
@@ -12,18 +19,90 @@ use tauri_plugin_store::StoreExt;
 use tracing::Dispatch;
 use tracing_subscriber::layer::SubscriberExt;
 
-fn ledger_dir(app: &tauri::AppHandle) -> std::path::PathBuf {
-    app.path()
-        .app_data_dir()
-        .expect("no app data dir")
-        .join("ledger.db")
+/// Stable, filesystem-friendly folder name for a network, used to keep each
+/// network's stores under their own subfolder of the app data dir.
+fn network_slug(network: NetworkName) -> &'static str {
+    match network {
+        NetworkName::Mainnet => "mainnet",
+        NetworkName::Preprod => "preprod",
+        NetworkName::Preview => "preview",
+        _ => "testnet",
+    }
 }
 
-fn chain_dir(app: &tauri::AppHandle) -> std::path::PathBuf {
+/// Value stored under the `"network"` key in `store.json`.
+fn network_label(network: NetworkName) -> &'static str {
+    match network {
+        NetworkName::Mainnet => "Mainnet",
+        NetworkName::Preprod => "PreProd",
+        NetworkName::Preview => "Preview",
+        _ => "PreProd",
+    }
+}
+
+fn network_dir(app: &tauri::AppHandle, network: NetworkName) -> std::path::PathBuf {
     app.path()
         .app_data_dir()
         .expect("no app data dir")
-        .join("chain.db")
+        .join(network_slug(network))
+}
+
+fn ledger_dir(app: &tauri::AppHandle, network: NetworkName) -> std::path::PathBuf {
+    network_dir(app, network).join("ledger.db")
+}
+
+fn chain_dir(app: &tauri::AppHandle, network: NetworkName) -> std::path::PathBuf {
+    network_dir(app, network).join("chain.db")
+}
+
+/// Per-network directory holding that network's snapshot archives, so a
+/// snapshot of one network can never be restored into another's stores.
+fn snapshots_dir(app: &tauri::AppHandle, network: NetworkName) -> std::path::PathBuf {
+    network_dir(app, network).join("snapshots")
+}
+
+/// The network currently selected in `store.json`, defaulting to Preprod.
+fn network_from_store(app: &tauri::AppHandle) -> NetworkName {
+    let Ok(store) = app.store("store.json") else {
+        return NetworkName::Preprod;
+    };
+    let value = store
+        .get("network")
+        .and_then(|v| v.get("value").and_then(|v| v.as_str().map(str::to_string)));
+    match value.as_deref() {
+        Some("Mainnet") => NetworkName::Mainnet,
+        Some("Preview") => NetworkName::Preview,
+        _ => NetworkName::Preprod,
+    }
+}
+
+/// Whether LAN peer-to-peer snapshot sharing is enabled in `store.json`.
+fn p2p_sharing_enabled(app: &tauri::AppHandle) -> bool {
+    app.store("store.json")
+        .ok()
+        .and_then(|store| store.get("p2p_sharing"))
+        .and_then(|v| v.get("value").and_then(serde_json::Value::as_bool).or_else(|| v.as_bool()))
+        .unwrap_or(false)
+}
+
+/// Handle to the running node thread, kept in Tauri managed state so the node
+/// can be torn down and restarted (e.g. when the user switches network).
+#[derive(Default)]
+struct NodeManager(std::sync::Mutex<Option<RunningNode>>);
+
+struct RunningNode {
+    network: NetworkName,
+    shutdown: tokio::sync::oneshot::Sender<()>,
+    handle: std::thread::JoinHandle<()>,
+}
+
+/// Latest observed chain position, kept so snapshot manifests can record the
+/// tip and so `create_snapshot` knows which epoch to package.
+#[derive(Default)]
+struct NodeStatus {
+    tip_slot: AtomicU64,
+    epoch: AtomicU64,
+    tip_hash: std::sync::Mutex<String>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -36,6 +115,33 @@ pub enum AppEvent {
     Runtime(RuntimeEvent),
 }
 
+impl AppEvent {
+    /// Short, stable event kind used as the key for per-sink event filters.
+    fn kind(&self) -> &'static str {
+        match self {
+            AppEvent::Bootstrap(e) => match e {
+                BootstrapEvent::DownloadingShapshot { .. } => "downloading_snapshot",
+                BootstrapEvent::SnapshotsDownloaded {} => "snapshots_downloaded",
+                BootstrapEvent::ImportingSnapshots {} => "importing_snapshots",
+                BootstrapEvent::ImportingSnapshot { .. } => "importing_snapshot",
+                BootstrapEvent::ImportedSnapshot { .. } => "imported_snapshot",
+                BootstrapEvent::ImportedSnapshots {} => "imported_snapshots",
+                BootstrapEvent::CreatingSnapshot { .. } => "creating_snapshot",
+                BootstrapEvent::SnapshotCreated { .. } => "snapshot_created",
+            },
+            AppEvent::Runtime(e) => match e {
+                RuntimeEvent::Starting { .. } => "starting",
+                RuntimeEvent::CreatingState {} => "creating_state",
+                RuntimeEvent::EpochTransition { .. } => "epoch_transition",
+                RuntimeEvent::TipCaughtUp { .. } => "tip_caught_up",
+                RuntimeEvent::TipSyncing { .. } => "tip_syncing",
+                RuntimeEvent::AppliedBlock { .. } => "applied_block",
+                RuntimeEvent::AppliedTransactions { .. } => "applied_transactions",
+            },
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Clone)]
 #[serde(tag = "kind")]
 pub enum BootstrapEvent {
@@ -56,6 +162,12 @@ pub enum BootstrapEvent {
 
     #[serde(rename = "imported_snapshots")]
     ImportedSnapshots {},
+
+    #[serde(rename = "creating_snapshot")]
+    CreatingSnapshot { epoch: Epoch },
+
+    #[serde(rename = "snapshot_created")]
+    SnapshotCreated { epoch: Epoch },
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -75,16 +187,43 @@ pub enum RuntimeEvent {
 
     #[serde(rename = "tip_syncing")]
     TipSyncing { slot: Slot },
+
+    #[serde(rename = "applied_block")]
+    AppliedBlock { slot: Slot, hash: String },
+
+    #[serde(rename = "applied_transactions")]
+    AppliedTransactions {
+        slot: Slot,
+        block: String,
+        transactions: Vec<String>,
+    },
 }
 
-fn slot_from_point(line: &serde_json::Value, field: &str) -> Slot {
+fn slot_from_point(line: &serde_json::Value, field: &str) -> u64 {
     line.get(field)
         .unwrap_or(&serde_json::Value::Null)
         .as_str()
         .and_then(|obj| obj.split(".").next())
         .and_then(|slot_val| slot_val.parse::<u64>().ok())
         .unwrap_or_default()
-        .into()
+}
+
+/// A point is serialised as `"<slot>.<hash>"`; pull out the block hash.
+fn hash_from_point(line: &serde_json::Value, field: &str) -> Option<String> {
+    line.get(field)
+        .and_then(|v| v.as_str())
+        .and_then(|point| point.split_once('.'))
+        .map(|(_, hash)| hash.to_string())
+}
+
+/// Record the latest tip so snapshot manifests and peer handshakes carry a
+/// meaningful position. The hash is only overwritten when one is present.
+fn update_tip(app: &tauri::AppHandle, slot: u64, hash: Option<String>) {
+    let status = app.state::<NodeStatus>();
+    status.tip_slot.store(slot, Ordering::Relaxed);
+    if let Some(hash) = hash {
+        *status.tip_hash.lock().unwrap() = hash;
+    }
 }
 
 fn emit_logs(app: &tauri::AppHandle, line: serde_json::Value) {
@@ -137,15 +276,16 @@ fn emit_logs(app: &tauri::AppHandle, line: serde_json::Value) {
             // tip":{"hash":"d6fe6439aed8bddc10eec22c1575bf0648e4a76125387d9e985e9a3f8342870d","slot":70070379}
             let tip = line
                 .get("tip")
-                .unwrap_or_default()
-                .as_object()
-                .unwrap()
-                .get("slot")
-                .unwrap_or_default()
-                .as_u64()
-                .unwrap_or_default()
-                .into();
-            Some(AppEvent::Runtime(RuntimeEvent::Starting { tip }))
+                .and_then(|v| v.get("slot"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default();
+            let hash = line
+                .get("tip")
+                .and_then(|v| v.get("hash"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            update_tip(app, tip, hash);
+            Some(AppEvent::Runtime(RuntimeEvent::Starting { tip: tip.into() }))
         }
         "new.known_snapshots" => Some(AppEvent::Runtime(RuntimeEvent::CreatingState {})),
         "epoch_transition" => {
@@ -153,32 +293,78 @@ fn emit_logs(app: &tauri::AppHandle, line: serde_json::Value) {
                 .get("from")
                 .unwrap_or_default()
                 .as_u64()
-                .unwrap_or_default()
-                .into();
+                .unwrap_or_default();
             let into = line
                 .get("into")
                 .unwrap_or_default()
                 .as_u64()
-                .unwrap_or_default()
-                .into();
+                .unwrap_or_default();
+            app.state::<NodeStatus>().epoch.store(into, Ordering::Relaxed);
+            // Package a snapshot of the epoch that just completed, off-thread so
+            // the node keeps running.
+            spawn_snapshot(app.clone(), from);
             Some(AppEvent::Runtime(RuntimeEvent::EpochTransition {
-                from,
-                into,
+                from: from.into(),
+                into: into.into(),
             }))
         }
         "track_peers.caught_up.new_tip" => {
             let slot = slot_from_point(&line, "point");
-            Some(AppEvent::Runtime(RuntimeEvent::TipCaughtUp { slot }))
+            update_tip(app, slot, hash_from_point(&line, "point"));
+            Some(AppEvent::Runtime(RuntimeEvent::TipCaughtUp { slot: slot.into() }))
         }
         "track_peers.syncing.new_tip" => {
             let slot = slot_from_point(&line, "point");
-            Some(AppEvent::Runtime(RuntimeEvent::TipSyncing { slot }))
+            update_tip(app, slot, hash_from_point(&line, "point"));
+            Some(AppEvent::Runtime(RuntimeEvent::TipSyncing { slot: slot.into() }))
+        }
+        "apply.block" => {
+            let slot = slot_from_point(&line, "point");
+            let hash = line
+                .get("hash")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            Some(AppEvent::Runtime(RuntimeEvent::AppliedBlock {
+                slot: slot.into(),
+                hash,
+            }))
         }
         _ => None,
     };
-    let _ = if let Some(event) = event {
-        let _ = app.emit("amaru", event);
-    };
+
+    let mut events: Vec<AppEvent> = event.into_iter().collect();
+
+    // An applied block also carries its transaction hashes; surface them as a
+    // separate event so tx-level sinks can filter on them independently.
+    if name == "apply.block" {
+        let transactions: Vec<String> = line
+            .get("transactions")
+            .and_then(|v| v.as_array())
+            .map(|txs| {
+                txs.iter()
+                    .filter_map(|tx| tx.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !transactions.is_empty() {
+            let block = line
+                .get("hash")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            events.push(AppEvent::Runtime(RuntimeEvent::AppliedTransactions {
+                slot: slot_from_point(&line, "point").into(),
+                block,
+                transactions,
+            }));
+        }
+    }
+
+    let sinks = app.state::<SinkSet>();
+    for event in &events {
+        sinks.dispatch(event);
+    }
 }
 
 #[tauri::command]
@@ -194,21 +380,110 @@ fn clear_app_data_dir(app: AppHandle) -> Result<(), String> {
 
 #[tauri::command]
 fn clear_dbs(app: AppHandle) -> Result<(), String> {
-    let ledger_dir = ledger_dir(&app);
+    let network = network_from_store(&app);
+
+    let ledger_dir = ledger_dir(&app, network);
 
     if ledger_dir.exists() {
         std::fs::remove_dir_all(&ledger_dir).map_err(|e| e.to_string())?;
     }
 
-    let chain_dir = chain_dir(&app);
+    let chain_dir = chain_dir(&app, network);
 
-    if ledger_dir.exists() {
+    if chain_dir.exists() {
         std::fs::remove_dir_all(&chain_dir).map_err(|e| e.to_string())?;
     }
 
     Ok(())
 }
 
+/// Stop the running node thread, if any, signalling a clean shutdown and
+/// waiting for the thread to unwind before returning.
+fn stop_node(app: &AppHandle) {
+    let running = app.state::<NodeManager>().0.lock().unwrap().take();
+    if let Some(running) = running {
+        // Best-effort: if the receiver is already gone the thread has exited.
+        let _ = running.shutdown.send(());
+        let _ = running.handle.join();
+    }
+}
+
+/// Package a snapshot of `epoch` on a background thread, emitting
+/// `CreatingSnapshot`/`SnapshotCreated` around the work so the UI can show
+/// progress. Errors are logged rather than surfaced: snapshotting is best
+/// effort and must never take the node down.
+fn spawn_snapshot(app: AppHandle, epoch: u64) {
+    std::thread::spawn(move || {
+        let status = app.state::<NodeStatus>();
+        let tip_slot = status.tip_slot.load(Ordering::Relaxed);
+        let tip_hash = status.tip_hash.lock().unwrap().clone();
+
+        let network = network_from_store(&app);
+        let dir = snapshots_dir(&app, network);
+        let ledger_dir = ledger_dir(&app, network);
+        let chain_dir = chain_dir(&app, network);
+
+        let _ = app.emit(
+            "amaru",
+            AppEvent::Bootstrap(BootstrapEvent::CreatingSnapshot {
+                epoch: epoch.into(),
+            }),
+        );
+        match snapshot::package(
+            &dir,
+            &ledger_dir,
+            &chain_dir,
+            network_slug(network),
+            epoch,
+            tip_slot,
+            &tip_hash,
+        ) {
+            Ok(_) => {
+                let _ = app.emit(
+                    "amaru",
+                    AppEvent::Bootstrap(BootstrapEvent::SnapshotCreated {
+                        epoch: epoch.into(),
+                    }),
+                );
+            }
+            Err(e) => eprintln!("Snapshot of epoch {epoch} failed: {e}"),
+        }
+    });
+}
+
+/// Package a snapshot of the current epoch on demand.
+#[tauri::command]
+fn create_snapshot(app: AppHandle) -> Result<(), String> {
+    let epoch = app.state::<NodeStatus>().epoch.load(Ordering::Relaxed);
+    spawn_snapshot(app, epoch);
+    Ok(())
+}
+
+/// Switch the running node to `network`: persist the selection, tear down the
+/// current node thread, swap to that network's per-network stores and relaunch.
+#[tauri::command]
+fn set_network(app: AppHandle, network: NetworkName) -> Result<(), String> {
+    // Nothing to do if the node is already running on the requested network.
+    let current = app
+        .state::<NodeManager>()
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|running| running.network);
+    if current == Some(network) {
+        return Ok(());
+    }
+
+    let store = app.store("store.json").map_err(|e| e.to_string())?;
+    store.set("network", json!({ "value": network_label(network) }));
+
+    stop_node(&app);
+    launch_amaru(app, network);
+
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let collector = JsonTraceCollector::default();
@@ -218,23 +493,44 @@ pub fn run() {
     let _guard = tracing::dispatcher::set_global_default(dispatch);
 
     tauri::Builder::default()
+        .manage(NodeManager::default())
+        .manage(NodeStatus::default())
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_keep_screen_on::init())
         .setup(|app| {
             let store = app.store("store.json")?;
-            store.set("network", json!({ "value": "PreProd" }));
+            if store.get("network").is_none() {
+                store.set("network", json!({ "value": "PreProd" }));
+            }
 
             let window = app.get_webview_window("main").unwrap();
             window.open_devtools();
 
             let app_handle = app.handle().clone();
 
+            // Build the chain-event sinks from `store.json`, falling back to the
+            // frontend-only default when none are configured.
+            let sink_configs: Vec<SinkConfig> = store
+                .get("sinks")
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_else(SinkSet::default_configs);
+            let app_data = app.path().app_data_dir()?;
+            app.manage(SinkSet::from_configs(&app_handle, &app_data, &sink_configs));
+
+            // Advertise and serve snapshots to LAN peers for the whole session,
+            // not just during our own first-run fetch.
+            if p2p_sharing_enabled(&app_handle) {
+                let serve_handle = app_handle.clone();
+                tauri::async_runtime::spawn(p2p::serve_forever(serve_handle));
+            }
+
             //      clear_app_data_dir(app_handle.clone()).ok();
             //      clear_dbs(app_handle.clone()).ok();
 
+            let network = network_from_store(&app_handle);
             tauri::async_runtime::spawn(async move {
-                launch_amaru(app_handle.clone(), NetworkName::Preprod);
+                launch_amaru(app_handle.clone(), network);
                 loop {
                     let lines = collector.flush();
                     for line in lines {
@@ -246,7 +542,15 @@ pub fn run() {
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![clear_app_data_dir])
+        .invoke_handler(tauri::generate_handler![
+            clear_app_data_dir,
+            clear_dbs,
+            set_network,
+            create_snapshot,
+            get_node_config,
+            set_node_config,
+            start_node
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -267,36 +571,123 @@ fn peers_for_network(network: NetworkName) -> Vec<String> {
     }
 }
 
+/// `store.json` key holding the persisted configuration for a network.
+/// Configuration is keyed per network so switching network picks up that
+/// network's peers/stores rather than reusing the previous network's.
+fn node_config_key(network: NetworkName) -> String {
+    format!("node_config.{}", network_slug(network))
+}
+
+/// The persisted node configuration for `network`, or the defaults for that
+/// network when none has been stored yet.
+fn node_config(app: &AppHandle, network: NetworkName) -> NodeConfig {
+    app.store("store.json")
+        .ok()
+        .and_then(|store| store.get(node_config_key(network)))
+        .and_then(|v| serde_json::from_value::<NodeConfig>(v).ok())
+        .unwrap_or_else(|| NodeConfig {
+            network: network_label(network).to_string(),
+            peers: peers_for_network(network),
+            ledger_store: config::StoreKind::default(),
+            chain_store: config::StoreKind::default(),
+            migrate_chain_db: true,
+        })
+}
+
+/// Read the persisted node configuration for the currently-selected network,
+/// falling back to that network's defaults.
+#[tauri::command]
+fn get_node_config(app: AppHandle) -> NodeConfig {
+    node_config(&app, network_from_store(&app))
+}
+
+/// Persist a node configuration under its own network's key in `store.json`.
+#[tauri::command]
+fn set_node_config(app: AppHandle, config: NodeConfig) -> Result<(), String> {
+    let store = app.store("store.json").map_err(|e| e.to_string())?;
+    store.set(
+        node_config_key(config::parse_network(&config.network)),
+        serde_json::to_value(&config).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+/// Start (or restart) the node from the persisted configuration.
+#[tauri::command]
+fn start_node(app: AppHandle) -> Result<(), String> {
+    let network = network_from_store(&app);
+    stop_node(&app);
+    launch_amaru(app, network);
+    Ok(())
+}
+
 fn launch_amaru(app: AppHandle, network: NetworkName) {
-    std::thread::Builder::new()
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let thread_app = app.clone();
+    let handle = std::thread::Builder::new()
         .stack_size(8 * 1024 * 1024)
         .spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                let ledger_dir = ledger_dir(&app);
-                let chain_dir = chain_dir(&app);
+                let ledger_dir = ledger_dir(&thread_app, network);
+                let chain_dir = chain_dir(&thread_app, network);
                 if !ledger_dir.exists() {
-                    bootstrap(
-                        network,
-                        ledger_dir.clone(),
-                        chain_dir.clone(),
-                    )
-                    .await
-                    .unwrap();
+                    let dir = snapshots_dir(&thread_app, network);
+
+                    // Prefer restoring the newest local snapshot.
+                    let mut restored =
+                        snapshot::restore_latest(&dir, &ledger_dir, &chain_dir).ok().flatten();
+
+                    // Then try fetching one from a LAN peer, if sharing is on.
+                    if restored.is_none() && p2p_sharing_enabled(&thread_app) {
+                        match p2p::try_fetch(&thread_app, network_slug(network), &dir).await {
+                            Ok(Some(_)) => {
+                                restored = snapshot::restore_latest(&dir, &ledger_dir, &chain_dir)
+                                    .ok()
+                                    .flatten();
+                            }
+                            Ok(None) => {}
+                            Err(e) => eprintln!("Peer snapshot fetch failed: {e}"),
+                        }
+                    }
+
+                    // Finally fall back to downloading from the remote relay.
+                    if restored.is_none() {
+                        bootstrap(
+                            network,
+                            ledger_dir.clone(),
+                            chain_dir.clone(),
+                        )
+                        .await
+                        .unwrap();
+                    }
                 }
-                let config = Config {
-                    upstream_peers: peers_for_network(network),
-                    ledger_store: amaru::stages::StoreType::RocksDb(RocksDbConfig::new(ledger_dir)),
-                    chain_store: amaru::stages::StoreType::RocksDb(RocksDbConfig::new(chain_dir)),
-                    migrate_chain_db: true,
-                    ..Config::default()
+                let builder = IcarusNodeBuilder::from(&node_config(&thread_app, network));
+                let config = match builder.build(ledger_dir, chain_dir) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        eprintln!("Invalid node configuration: {e}");
+                        return;
+                    }
                 };
 
                 match build_and_run_network(config, None).await {
-                    Ok(running) => running.join().await,
+                    // Run until the node stops on its own or a teardown is requested.
+                    Ok(running) => {
+                        tokio::select! {
+                            _ = running.join() => {}
+                            _ = shutdown_rx => {}
+                        }
+                    }
                     Err(e) => eprintln!("Bootstrap failed: {}", e),
                 }
             });
         })
         .unwrap();
+
+    *app.state::<NodeManager>().0.lock().unwrap() = Some(RunningNode {
+        network,
+        shutdown: shutdown_tx,
+        handle,
+    });
 }
@@ -0,0 +1,367 @@
+//! LAN peer-to-peer snapshot sharing between icarus instances.
+//!
+//! First-run bootstrap normally pulls snapshots from a central relay, which is
+//! slow and redundant when another icarus node on the local network already
+//! synced the same network. This module discovers peer instances over mDNS,
+//! exchanges a small [`NodeInfo`] handshake (network, available snapshot
+//! epochs, tip), then fetches a compressed snapshot archive over a
+//! request/response protocol — chunked, with each chunk verified against the
+//! manifest's per-chunk hash — before the caller falls back to the remote
+//! `bootstrap`.
+//!
+//! [`serve_forever`] runs as a persistent background service so that a node
+//! which has already synced (the only node with a snapshot worth sharing) keeps
+//! advertising and answering requests, not just during its own first-run fetch.
+//! [`try_fetch`] is the short-lived client side used during bootstrap.
+//!
+//! Participation is gated behind the `"p2p_sharing"` flag in `store.json`.
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use libp2p::futures::StreamExt;
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{mdns, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::snapshot::{self, SnapshotManifest};
+use crate::{AppEvent, BootstrapEvent, NodeStatus};
+
+const PROTOCOL: StreamProtocol = StreamProtocol::new("/icarus/snapshot/1");
+
+/// Address the swarm listens on; port 0 lets the OS pick.
+const LISTEN_ADDR: &str = "/ip4/0.0.0.0/tcp/0";
+
+/// How long the client spends discovering peers and transferring before giving
+/// up and letting the caller fall back to the remote relay.
+const DEADLINE: Duration = Duration::from_secs(30);
+
+/// Handshake advertised by every participating instance.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeInfo {
+    pub network: String,
+    pub epochs: Vec<u64>,
+    pub tip_slot: u64,
+    pub tip_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Request {
+    Info,
+    Manifest { epoch: u64 },
+    Chunk { epoch: u64, index: u32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Response {
+    Info(NodeInfo),
+    Manifest(Option<SnapshotManifest>),
+    Chunk(Option<Vec<u8>>),
+}
+
+#[derive(libp2p::swarm::NetworkBehaviour)]
+struct Behaviour {
+    mdns: mdns::tokio::Behaviour,
+    rr: request_response::cbor::Behaviour<Request, Response>,
+}
+
+/// In-flight download from a single peer.
+struct Transfer {
+    manifest: SnapshotManifest,
+    chunks: Vec<Option<Vec<u8>>>,
+    next: u32,
+}
+
+/// Persistent service: advertise over mDNS and answer snapshot requests from
+/// peers for as long as the app runs, regardless of our own sync state. Runs
+/// until the swarm terminates (which it shouldn't) and logs fatal setup errors.
+pub async fn serve_forever(app: AppHandle) {
+    let mut swarm = match build_swarm() {
+        Ok(swarm) => swarm,
+        Err(e) => {
+            eprintln!("Peer sharing disabled: {e}");
+            return;
+        }
+    };
+    if let Err(e) = listen(&mut swarm) {
+        eprintln!("Peer sharing disabled: {e}");
+        return;
+    }
+
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                for (peer, addr) in peers {
+                    swarm.behaviour_mut().rr.add_address(&peer, addr);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Rr(request_response::Event::Message {
+                message: request_response::Message::Request { request, channel, .. },
+                ..
+            })) => {
+                let response = serve(&app, request);
+                let _ = swarm.behaviour_mut().rr.send_response(channel, response);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Try to fetch a snapshot for `network` from a LAN peer, installing it into
+/// `dir` (that network's snapshot folder) and returning the fetched epoch.
+/// Returns `Ok(None)` when no suitable peer is found or the window elapses, so
+/// the caller falls back to the remote bootstrap.
+pub async fn try_fetch(app: &AppHandle, network: &str, dir: &Path) -> Result<Option<u64>, String> {
+    let mut swarm = build_swarm()?;
+    listen(&mut swarm)?;
+
+    let mut transfer: Option<(libp2p::PeerId, Transfer)> = None;
+
+    let deadline = tokio::time::sleep(DEADLINE);
+    tokio::pin!(deadline);
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return Ok(None),
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                    for (peer, addr) in peers {
+                        swarm.behaviour_mut().rr.add_address(&peer, addr);
+                        swarm.behaviour_mut().rr.send_request(&peer, Request::Info);
+                    }
+                }
+                SwarmEvent::Behaviour(BehaviourEvent::Rr(request_response::Event::Message {
+                    peer,
+                    message,
+                })) => match message {
+                    // Answer peers even while fetching, so discovery is mutual.
+                    request_response::Message::Request { request, channel, .. } => {
+                        let response = serve(app, request);
+                        let _ = swarm.behaviour_mut().rr.send_response(channel, response);
+                    }
+                    request_response::Message::Response { response, .. } => {
+                        if let Some(epoch) = handle_response(
+                            app, dir, network, &mut swarm, peer, &mut transfer, response,
+                        )? {
+                            return Ok(Some(epoch));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+fn build_swarm() -> Result<libp2p::Swarm<Behaviour>, String> {
+    let swarm = libp2p::SwarmBuilder::with_new_identity()
+        .with_tokio()
+        .with_tcp(
+            Default::default(),
+            libp2p::noise::Config::new,
+            libp2p::yamux::Config::default,
+        )
+        .map_err(|e| e.to_string())?
+        .with_behaviour(|key| {
+            let mdns =
+                mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?;
+            let rr = request_response::cbor::Behaviour::new(
+                [(PROTOCOL, ProtocolSupport::Full)],
+                request_response::Config::default(),
+            );
+            Ok(Behaviour { mdns, rr })
+        })
+        .map_err(|e| e.to_string())?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(DEADLINE))
+        .build();
+    Ok(swarm)
+}
+
+fn listen(swarm: &mut libp2p::Swarm<Behaviour>) -> Result<(), String> {
+    let addr = LISTEN_ADDR
+        .parse()
+        .map_err(|e: libp2p::multiaddr::Error| e.to_string())?;
+    swarm.listen_on(addr).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Answer an inbound request using our local snapshots for the network we are
+/// *currently* serving, so we never advertise one network's epochs as another.
+fn serve(app: &AppHandle, request: Request) -> Response {
+    let network = crate::network_from_store(app);
+    let dir = crate::snapshots_dir(app, network);
+    match request {
+        Request::Info => {
+            let status = app.state::<NodeStatus>();
+            Response::Info(NodeInfo {
+                network: crate::network_slug(network).to_string(),
+                epochs: snapshot::available_epochs(&dir),
+                tip_slot: status.tip_slot.load(Ordering::Relaxed),
+                tip_hash: status.tip_hash.lock().unwrap().clone(),
+            })
+        }
+        Request::Manifest { epoch } => Response::Manifest(snapshot::load_manifest(&dir, epoch)),
+        Request::Chunk { epoch, index } => {
+            Response::Chunk(snapshot::read_chunk(&dir, epoch, index))
+        }
+    }
+}
+
+/// Drive the client state machine for a response. Returns `Some(epoch)` once a
+/// full, verified archive has been installed into `dir`.
+fn handle_response(
+    app: &AppHandle,
+    dir: &Path,
+    network: &str,
+    swarm: &mut libp2p::Swarm<Behaviour>,
+    peer: libp2p::PeerId,
+    transfer: &mut Option<(libp2p::PeerId, Transfer)>,
+    response: Response,
+) -> Result<Option<u64>, String> {
+    match response {
+        Response::Info(info) => {
+            // Only start a transfer if we aren't already mid-download and the
+            // peer serves the same network with at least one snapshot.
+            if transfer.is_none() && info.network == network {
+                if let Some(&epoch) = info.epochs.iter().max() {
+                    let _ = app.emit(
+                        "amaru",
+                        AppEvent::Bootstrap(BootstrapEvent::DownloadingShapshot {
+                            epoch: epoch.into(),
+                        }),
+                    );
+                    swarm
+                        .behaviour_mut()
+                        .rr
+                        .send_request(&peer, Request::Manifest { epoch });
+                }
+            }
+        }
+        Response::Manifest(Some(manifest)) => {
+            // Ignore a manifest for the wrong network; per-network dirs mean we
+            // must never install a mismatched snapshot.
+            if manifest.network != network {
+                return Ok(None);
+            }
+            let chunk_count = manifest.chunk_hashes.len().max(1);
+            let epoch = manifest.epoch;
+            *transfer = Some((
+                peer,
+                Transfer {
+                    manifest,
+                    chunks: vec![None; chunk_count],
+                    next: 0,
+                },
+            ));
+            swarm
+                .behaviour_mut()
+                .rr
+                .send_request(&peer, Request::Chunk { epoch, index: 0 });
+        }
+        Response::Chunk(Some(data)) => {
+            // Take ownership of the in-flight transfer so we can mutate it and
+            // hand it back (or drop it) without holding a borrow of `transfer`.
+            let Some((from, mut t)) = transfer.take() else {
+                return Ok(None);
+            };
+            if from != peer {
+                *transfer = Some((from, t));
+                return Ok(None);
+            }
+            let index = t.next as usize;
+            // Verify against the manifest's per-chunk hash before accepting;
+            // a mismatch means we abandon this peer and fall back.
+            if !chunk_matches(t.manifest.chunk_hashes.get(index), &data) {
+                return Ok(None);
+            }
+            if index < t.chunks.len() {
+                t.chunks[index] = Some(data);
+            }
+            t.next += 1;
+
+            if (t.next as usize) < t.manifest.chunk_hashes.len() {
+                let (epoch, index) = (t.manifest.epoch, t.next);
+                swarm
+                    .behaviour_mut()
+                    .rr
+                    .send_request(&peer, Request::Chunk { epoch, index });
+                *transfer = Some((from, t));
+            } else {
+                let archive = reassemble(&t.chunks, &t.manifest.checksum)?;
+                snapshot::install_received(dir, &t.manifest, &archive)?;
+                let _ = app.emit(
+                    "amaru",
+                    AppEvent::Bootstrap(BootstrapEvent::SnapshotsDownloaded {}),
+                );
+                return Ok(Some(t.manifest.epoch));
+            }
+        }
+        Response::Manifest(None) | Response::Chunk(None) => {
+            *transfer = None;
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `data` hashes to the `expected` per-chunk hash. A missing expected
+/// hash (chunk index out of range) never matches.
+fn chunk_matches(expected: Option<&String>, data: &[u8]) -> bool {
+    expected.is_some_and(|h| *h == sha256_hex(data))
+}
+
+/// Concatenate the received chunks and verify the whole archive against the
+/// manifest checksum. Errors if any chunk is missing or the checksum differs.
+fn reassemble(chunks: &[Option<Vec<u8>>], checksum: &str) -> Result<Vec<u8>, String> {
+    let mut archive = Vec::with_capacity(chunks.len() * snapshot::CHUNK);
+    for chunk in chunks {
+        archive.extend_from_slice(chunk.as_deref().ok_or("missing chunk in transfer")?);
+    }
+    if sha256_hex(&archive) != checksum {
+        return Err("peer snapshot checksum mismatch".into());
+    }
+    Ok(archive)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_matches_only_on_correct_hash() {
+        let data = b"hello world";
+        let hash = sha256_hex(data);
+        assert!(chunk_matches(Some(&hash), data));
+        assert!(!chunk_matches(Some(&hash), b"tampered"));
+        assert!(!chunk_matches(None, data));
+    }
+
+    #[test]
+    fn reassemble_joins_verified_chunks() {
+        let archive = b"the full archive bytes".to_vec();
+        let checksum = sha256_hex(&archive);
+        let chunks = vec![Some(b"the full ".to_vec()), Some(b"archive bytes".to_vec())];
+        assert_eq!(reassemble(&chunks, &checksum).unwrap(), archive);
+    }
+
+    #[test]
+    fn reassemble_rejects_checksum_mismatch() {
+        let chunks = vec![Some(b"corrupt".to_vec())];
+        assert!(reassemble(&chunks, &sha256_hex(b"expected")).is_err());
+    }
+
+    #[test]
+    fn reassemble_rejects_missing_chunk() {
+        let chunks = vec![Some(b"a".to_vec()), None];
+        assert!(reassemble(&chunks, &sha256_hex(b"a")).is_err());
+    }
+}
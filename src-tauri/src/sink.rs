@@ -0,0 +1,202 @@
+//! Chain-event sinks.
+//!
+//! `emit_logs` turns node trace lines into [`AppEvent`]s; this module fans
+//! those events out to any number of configured destinations — the embedded
+//! frontend, a newline-delimited JSON file, stdout, or an HTTP webhook — so
+//! downstream tooling (indexers, notifiers) can consume the node's activity
+//! and not just the UI. This is the Oura "read chain, fan out structured
+//! events to configurable sinks" pattern.
+//!
+//! Which sinks are active, and an optional per-sink event filter, are read
+//! from `store.json` under the `"sinks"` key.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::AppEvent;
+
+/// A destination for chain events.
+pub trait Sink: Send + Sync {
+    /// Deliver a single event. Implementations must not panic or block the
+    /// dispatch loop for long; slow transports should hand off to a thread.
+    fn deliver(&self, event: &AppEvent);
+}
+
+/// Declarative sink configuration, as stored in `store.json`:
+///
+/// ```json
+/// { "sinks": [
+///   { "type": "tauri" },
+///   { "type": "file", "path": "events.ndjson", "filter": ["applied_block"] },
+///   { "type": "webhook", "url": "https://example.test/hook" }
+/// ]}
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    Tauri {
+        filter: Option<Vec<String>>,
+    },
+    File {
+        path: String,
+        filter: Option<Vec<String>>,
+    },
+    Stdout {
+        filter: Option<Vec<String>>,
+    },
+    Webhook {
+        url: String,
+        filter: Option<Vec<String>>,
+    },
+}
+
+/// The set of active sinks, dispatched to in order for every event.
+pub struct SinkSet {
+    sinks: Vec<(Option<HashSet<String>>, Box<dyn Sink>)>,
+}
+
+impl SinkSet {
+    /// Build the active sinks from configuration. `app_data` is used to resolve
+    /// relative file paths. Unbuildable sinks (e.g. an unopenable file) are
+    /// logged and skipped rather than aborting startup.
+    pub fn from_configs(app: &AppHandle, app_data: &Path, configs: &[SinkConfig]) -> Self {
+        let mut sinks: Vec<(Option<HashSet<String>>, Box<dyn Sink>)> = Vec::new();
+        for config in configs {
+            let (filter, sink): (Option<Vec<String>>, Option<Box<dyn Sink>>) = match config {
+                SinkConfig::Tauri { filter } => (
+                    filter.clone(),
+                    Some(Box::new(TauriSink { app: app.clone() })),
+                ),
+                SinkConfig::Stdout { filter } => (filter.clone(), Some(Box::new(StdoutSink))),
+                SinkConfig::File { path, filter } => {
+                    let resolved = app_data.join(path);
+                    match FileSink::open(&resolved) {
+                        Ok(sink) => (filter.clone(), Some(Box::new(sink) as Box<dyn Sink>)),
+                        Err(e) => {
+                            eprintln!("Skipping file sink {}: {e}", resolved.display());
+                            (None, None)
+                        }
+                    }
+                }
+                SinkConfig::Webhook { url, filter } => (
+                    filter.clone(),
+                    Some(Box::new(WebhookSink { url: url.clone() })),
+                ),
+            };
+            if let Some(sink) = sink {
+                sinks.push((filter.map(|f| f.into_iter().collect()), sink));
+            }
+        }
+        SinkSet { sinks }
+    }
+
+    /// The default configuration when `store.json` has no `"sinks"` key: emit
+    /// to the frontend only, preserving the previous behaviour.
+    pub fn default_configs() -> Vec<SinkConfig> {
+        vec![SinkConfig::Tauri { filter: None }]
+    }
+
+    /// Deliver `event` to every sink whose filter admits it.
+    pub fn dispatch(&self, event: &AppEvent) {
+        let kind = event.kind();
+        for (filter, sink) in &self.sinks {
+            if filter.as_ref().is_none_or(|f| f.contains(kind)) {
+                sink.deliver(event);
+            }
+        }
+    }
+}
+
+/// Emits to the embedded frontend over the `amaru` Tauri event.
+struct TauriSink {
+    app: AppHandle,
+}
+
+impl Sink for TauriSink {
+    fn deliver(&self, event: &AppEvent) {
+        let _ = self.app.emit("amaru", event);
+    }
+}
+
+/// Appends one JSON object per line to a file.
+struct FileSink {
+    writer: Mutex<BufWriter<std::fs::File>>,
+}
+
+impl FileSink {
+    fn open(path: &Path) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        Ok(FileSink {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl Sink for FileSink {
+    fn deliver(&self, event: &AppEvent) {
+        if let Ok(line) = serde_json::to_string(event) {
+            let mut writer = self.writer.lock().unwrap();
+            let _ = writeln!(writer, "{line}");
+            let _ = writer.flush();
+        }
+    }
+}
+
+/// Writes one JSON object per line to stdout.
+struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn deliver(&self, event: &AppEvent) {
+        if let Ok(line) = serde_json::to_string(event) {
+            println!("{line}");
+        }
+    }
+}
+
+/// POSTs each event as JSON to a webhook, retrying a few times with backoff on
+/// transient failures. The request is handed to a background thread so a slow
+/// or unreachable endpoint never stalls the dispatch loop.
+struct WebhookSink {
+    url: String,
+}
+
+impl Sink for WebhookSink {
+    fn deliver(&self, event: &AppEvent) {
+        let Ok(body) = serde_json::to_vec(event) else {
+            return;
+        };
+        let url = self.url.clone();
+        std::thread::spawn(move || {
+            let client = reqwest::blocking::Client::new();
+            let mut backoff = std::time::Duration::from_millis(250);
+            for attempt in 0..4 {
+                let sent = client
+                    .post(&url)
+                    .header("content-type", "application/json")
+                    .body(body.clone())
+                    .send();
+                match sent {
+                    Ok(response) if response.status().is_success() => return,
+                    _ if attempt < 3 => {
+                        std::thread::sleep(backoff);
+                        backoff *= 2;
+                    }
+                    _ => eprintln!("Webhook {url} failed after retries"),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,456 @@
+//! Local RocksDB snapshot packaging.
+//!
+//! Mirrors the Solana snapshot-packaging model (checkpoint + package +
+//! compress + manifest) for Amaru's RocksDB stores: we take a consistent
+//! RocksDB checkpoint of both the ledger and chain stores (hard-linked SST
+//! files, so the node is never blocked), stream the checkpoint directory into
+//! a zstd-compressed tar archive named by epoch, and write a small JSON
+//! manifest alongside it. The newest valid archive can be restored on startup
+//! instead of downloading from the relay.
+//!
+//! Snapshots are namespaced per network by the caller: every function operates
+//! on a `dir` that already points at that network's `snapshots` folder, so a
+//! Preprod snapshot can never be restored into a Mainnet ledger.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Number of snapshot archives to keep; older ones are rotated out.
+const KEEP: usize = 3;
+
+/// Chunk size used when transferring archives between peers.
+pub const CHUNK: usize = 1 << 20;
+
+/// Metadata written next to each `snapshot-<epoch>.tar.zst` archive.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotManifest {
+    /// Network slug the snapshot was produced for (e.g. `"preprod"`).
+    pub network: String,
+    pub epoch: u64,
+    pub tip_slot: u64,
+    pub tip_hash: String,
+    pub files: Vec<String>,
+    /// SHA-256 of the compressed archive, hex-encoded.
+    pub checksum: String,
+    /// Per-chunk SHA-256 hashes (of [`CHUNK`]-sized pieces of the archive),
+    /// used to verify peer-to-peer transfers incrementally.
+    pub chunk_hashes: Vec<String>,
+}
+
+/// Path to the archive for `epoch` (whether or not it exists).
+pub fn archive_path(dir: &Path, epoch: u64) -> PathBuf {
+    dir.join(archive_name(epoch))
+}
+
+/// Epochs for which a `snapshot-<epoch>.json` manifest file exists, newest
+/// first. Only file names are checked; the manifests are not parsed.
+pub fn available_epochs(dir: &Path) -> Vec<u64> {
+    if !dir.exists() {
+        return Vec::new();
+    }
+    let mut epochs: Vec<u64> = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if let Some(epoch) = name
+                .to_string_lossy()
+                .strip_prefix("snapshot-")
+                .and_then(|rest| rest.strip_suffix(".json"))
+                .and_then(|e| e.parse::<u64>().ok())
+            {
+                epochs.push(epoch);
+            }
+        }
+    }
+    epochs.sort_unstable_by(|a, b| b.cmp(a));
+    epochs
+}
+
+/// Read the `index`-th [`CHUNK`]-sized piece of `epoch`'s archive without
+/// loading the whole file, for serving chunked transfers. Returns `None` when
+/// the archive is missing or `index` is past the end.
+pub fn read_chunk(dir: &Path, epoch: u64, index: u32) -> Option<Vec<u8>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = File::open(archive_path(dir, epoch)).ok()?;
+    let len = file.metadata().ok()?.len();
+    let offset = (index as u64).checked_mul(CHUNK as u64)?;
+    if offset >= len {
+        return None;
+    }
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let take = (len - offset).min(CHUNK as u64) as usize;
+    let mut buf = vec![0u8; take];
+    file.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Per-chunk SHA-256 hashes of `path`, in order, for chunked transfer.
+pub fn chunk_hashes(path: &Path) -> Result<Vec<String>, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    Ok(bytes.chunks(CHUNK).map(sha256_hex).collect())
+}
+
+/// Record an archive received from a peer along with its manifest, so it can
+/// be restored and re-shared like a locally-produced one.
+pub fn install_received(
+    dir: &Path,
+    manifest: &SnapshotManifest,
+    archive: &[u8],
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(archive_name(manifest.epoch)), archive).map_err(|e| e.to_string())?;
+    let manifest_json = serde_json::to_vec_pretty(manifest).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(manifest_name(manifest.epoch)), manifest_json)
+        .map_err(|e| e.to_string())?;
+    rotate(dir)
+}
+
+/// Load the manifest for `epoch`, if present.
+pub fn load_manifest(dir: &Path, epoch: u64) -> Option<SnapshotManifest> {
+    let bytes = std::fs::read(dir.join(manifest_name(epoch))).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn archive_name(epoch: u64) -> String {
+    format!("snapshot-{epoch}.tar.zst")
+}
+
+fn manifest_name(epoch: u64) -> String {
+    format!("snapshot-{epoch}.json")
+}
+
+/// Take a consistent checkpoint of the `ledger_db` and `chain_db` stores,
+/// package them into `dir/snapshot-<epoch>.tar.zst`, write the manifest and
+/// rotate older archives. Returns the path to the archive that was created.
+#[allow(clippy::too_many_arguments)]
+pub fn package(
+    dir: &Path,
+    ledger_db: &Path,
+    chain_db: &Path,
+    network: &str,
+    epoch: u64,
+    tip_slot: u64,
+    tip_hash: &str,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    // Checkpoint both stores into sibling subdirs of a temp dir; hard-linked
+    // SSTs keep this cheap and non-blocking for the node still writing.
+    let checkpoint_dir = dir.join(format!(".checkpoint-{epoch}"));
+    if checkpoint_dir.exists() {
+        std::fs::remove_dir_all(&checkpoint_dir).map_err(|e| e.to_string())?;
+    }
+    // `create_checkpoint` wants the target's parent to exist but the target
+    // itself to be absent, so create the containing dir up front.
+    std::fs::create_dir_all(&checkpoint_dir).map_err(|e| e.to_string())?;
+    checkpoint(ledger_db, &checkpoint_dir.join("ledger"))?;
+    checkpoint(chain_db, &checkpoint_dir.join("chain"))?;
+
+    let files = list_files(&checkpoint_dir)?;
+
+    // Stream the checkpoint into a zstd-compressed tar archive.
+    let archive_path = dir.join(archive_name(epoch));
+    {
+        let file = File::create(&archive_path).map_err(|e| e.to_string())?;
+        let encoder = zstd::Encoder::new(file, 0)
+            .map_err(|e| e.to_string())?
+            .auto_finish();
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_dir_all(".", &checkpoint_dir)
+            .map_err(|e| e.to_string())?;
+        tar.finish().map_err(|e| e.to_string())?;
+    }
+    let _ = std::fs::remove_dir_all(&checkpoint_dir);
+
+    let checksum = checksum(&archive_path)?;
+    let chunk_hashes = chunk_hashes(&archive_path)?;
+    let manifest = SnapshotManifest {
+        network: network.to_string(),
+        epoch,
+        tip_slot,
+        tip_hash: tip_hash.to_string(),
+        files,
+        checksum,
+        chunk_hashes,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(manifest_name(epoch)), manifest_json).map_err(|e| e.to_string())?;
+
+    rotate(dir)?;
+
+    Ok(archive_path)
+}
+
+/// Restore the newest local snapshot (the highest-epoch manifest that parses)
+/// into `ledger_db`/`chain_db`, returning the restored epoch on success.
+/// Returns `Ok(None)` when no manifest is present, and an error when that
+/// snapshot's archive is missing or fails its checksum; older snapshots are
+/// not tried.
+pub fn restore_latest(
+    dir: &Path,
+    ledger_db: &Path,
+    chain_db: &Path,
+) -> Result<Option<u64>, String> {
+    let Some(manifest) = latest_manifest(dir)? else {
+        return Ok(None);
+    };
+
+    let archive_path = dir.join(archive_name(manifest.epoch));
+    if checksum(&archive_path)? != manifest.checksum {
+        return Err(format!(
+            "snapshot-{} checksum mismatch; refusing to restore",
+            manifest.epoch
+        ));
+    }
+
+    // Unpack into a temp dir, then swap both stores into place so a restore
+    // leaves the ledger and chain stores consistent with each other.
+    let staging = dir.join(format!(".restore-{}", manifest.epoch));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+    }
+    std::fs::create_dir_all(&staging).map_err(|e| e.to_string())?;
+    let file = File::open(&archive_path).map_err(|e| e.to_string())?;
+    let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+    tar::Archive::new(decoder)
+        .unpack(&staging)
+        .map_err(|e| e.to_string())?;
+
+    swap_into_place(&staging.join("ledger"), ledger_db)?;
+    swap_into_place(&staging.join("chain"), chain_db)?;
+    let _ = std::fs::remove_dir_all(&staging);
+
+    Ok(Some(manifest.epoch))
+}
+
+/// Move `from` to `to`, replacing any existing store directory.
+fn swap_into_place(from: &Path, to: &Path) -> Result<(), String> {
+    if to.exists() {
+        std::fs::remove_dir_all(to).map_err(|e| e.to_string())?;
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(from, to).map_err(|e| e.to_string())
+}
+
+/// Highest-epoch manifest that parses cleanly, if any.
+fn latest_manifest(dir: &Path) -> Result<Option<SnapshotManifest>, String> {
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let mut best: Option<SnapshotManifest> = None;
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(&path) else {
+            continue;
+        };
+        let Ok(manifest) = serde_json::from_slice::<SnapshotManifest>(&bytes) else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|b| manifest.epoch > b.epoch) {
+            best = Some(manifest);
+        }
+    }
+    Ok(best)
+}
+
+/// Keep only the `KEEP` newest archives (and their manifests), by epoch.
+fn rotate(dir: &Path) -> Result<(), String> {
+    let mut epochs: Vec<u64> = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let name = entry.map_err(|e| e.to_string())?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(epoch) = name
+            .strip_prefix("snapshot-")
+            .and_then(|rest| rest.strip_suffix(".json"))
+            .and_then(|e| e.parse::<u64>().ok())
+        {
+            epochs.push(epoch);
+        }
+    }
+    epochs.sort_unstable_by(|a, b| b.cmp(a));
+    for epoch in epochs.into_iter().skip(KEEP) {
+        let _ = std::fs::remove_file(dir.join(archive_name(epoch)));
+        let _ = std::fs::remove_file(dir.join(manifest_name(epoch)));
+    }
+    Ok(())
+}
+
+fn checkpoint(db_path: &Path, target: &Path) -> Result<(), String> {
+    use rocksdb::checkpoint::Checkpoint;
+    let opts = rocksdb::Options::default();
+    // Open with the store's real column family set so multi-CF stores (as a
+    // UTXO ledger typically is) don't error with "you have to open all column
+    // families"; `list_cf` returns `["default"]` for a single-CF store.
+    let cfs = rocksdb::DB::list_cf(&opts, db_path)
+        .unwrap_or_else(|_| vec!["default".to_string()]);
+    // Open a primary handle and hard-link a consistent set of SSTs into
+    // `target`. `create_checkpoint` flushes the active memtable to produce a
+    // consistent SST set, which a read-only secondary instance cannot do (it
+    // fails with `NotSupported`), so a primary handle is required. The open
+    // fails loudly if the store is still locked, which `package`'s caller
+    // logs rather than swallowing.
+    let db = rocksdb::DB::open_cf(&opts, db_path, &cfs).map_err(|e| e.to_string())?;
+    let checkpoint = Checkpoint::new(&db).map_err(|e| e.to_string())?;
+    checkpoint
+        .create_checkpoint(target)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn list_files(dir: &Path) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let name = entry.map_err(|e| e.to_string())?.file_name();
+        files.push(name.to_string_lossy().into_owned());
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn checksum(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    Ok(sha256_hex(&bytes))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unique scratch directory for a test, removed on drop.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(tag: &str) -> Self {
+            use std::sync::atomic::{AtomicU64, Ordering};
+            static COUNTER: AtomicU64 = AtomicU64::new(0);
+            let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let dir = std::env::temp_dir().join(format!("icarus-snapshot-{tag}-{n}"));
+            std::fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn touch(path: &Path) {
+        std::fs::write(path, b"x").unwrap();
+    }
+
+    #[test]
+    fn rotate_keeps_only_the_newest_archives() {
+        let scratch = Scratch::new("rotate");
+        let dir = &scratch.0;
+        for epoch in 1..=KEEP as u64 + 2 {
+            touch(&dir.join(archive_name(epoch)));
+            touch(&dir.join(manifest_name(epoch)));
+        }
+        rotate(dir).unwrap();
+
+        assert_eq!(available_epochs(dir).len(), KEEP);
+        // The two oldest epochs are gone, archives and manifests alike.
+        assert!(!dir.join(manifest_name(1)).exists());
+        assert!(!dir.join(archive_name(1)).exists());
+        assert!(dir.join(manifest_name(KEEP as u64 + 2)).exists());
+    }
+
+    #[test]
+    fn available_epochs_is_sorted_newest_first() {
+        let scratch = Scratch::new("epochs");
+        let dir = &scratch.0;
+        for epoch in [4u64, 1, 7] {
+            touch(&dir.join(manifest_name(epoch)));
+        }
+        assert_eq!(available_epochs(dir), vec![7, 4, 1]);
+    }
+
+    #[test]
+    fn chunk_hashes_splits_and_matches_per_chunk() {
+        let scratch = Scratch::new("chunks");
+        let path = scratch.0.join("archive.bin");
+        // Two and a half chunks => three hashes.
+        let data = vec![7u8; CHUNK * 2 + CHUNK / 2];
+        std::fs::write(&path, &data).unwrap();
+
+        let hashes = chunk_hashes(&path).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[0], sha256_hex(&data[..CHUNK]));
+        assert_eq!(hashes[2], sha256_hex(&data[CHUNK * 2..]));
+    }
+
+    #[test]
+    fn read_chunk_reads_single_piece_without_loading_whole_archive() {
+        let scratch = Scratch::new("readchunk");
+        let dir = &scratch.0;
+        let data = vec![9u8; CHUNK + CHUNK / 2];
+        std::fs::write(dir.join(archive_name(5)), &data).unwrap();
+
+        assert_eq!(read_chunk(dir, 5, 0).unwrap(), data[..CHUNK]);
+        assert_eq!(read_chunk(dir, 5, 1).unwrap(), data[CHUNK..]);
+        // Past the end and missing archives yield nothing.
+        assert!(read_chunk(dir, 5, 2).is_none());
+        assert!(read_chunk(dir, 99, 0).is_none());
+    }
+
+    fn write_store(path: &Path, pairs: &[(&str, &str)]) {
+        let db = rocksdb::DB::open_default(path).unwrap();
+        for (k, v) in pairs {
+            db.put(k.as_bytes(), v.as_bytes()).unwrap();
+        }
+    }
+
+    fn read_value(path: &Path, key: &str) -> Option<String> {
+        let db = rocksdb::DB::open_default(path).unwrap();
+        db.get(key.as_bytes())
+            .unwrap()
+            .map(|v| String::from_utf8(v).unwrap())
+    }
+
+    #[test]
+    fn package_then_restore_round_trips_both_stores() {
+        let scratch = Scratch::new("roundtrip");
+        let root = &scratch.0;
+        let ledger = root.join("ledger");
+        let chain = root.join("chain");
+        write_store(&ledger, &[("ledger-key", "ledger-value")]);
+        write_store(&chain, &[("chain-key", "chain-value")]);
+
+        let snapshots = root.join("snapshots");
+        let archive = package(&snapshots, &ledger, &chain, "preprod", 42, 70070379, "d6fe").unwrap();
+        assert!(archive.exists());
+        let manifest = load_manifest(&snapshots, 42).unwrap();
+        assert_eq!(manifest.network, "preprod");
+        assert_eq!(manifest.epoch, 42);
+
+        // Restore into fresh store directories and assert both came back intact.
+        let restored_ledger = root.join("restored-ledger");
+        let restored_chain = root.join("restored-chain");
+        let epoch = restore_latest(&snapshots, &restored_ledger, &restored_chain).unwrap();
+        assert_eq!(epoch, Some(42));
+        assert_eq!(
+            read_value(&restored_ledger, "ledger-key").as_deref(),
+            Some("ledger-value")
+        );
+        assert_eq!(
+            read_value(&restored_chain, "chain-key").as_deref(),
+            Some("chain-value")
+        );
+    }
+}